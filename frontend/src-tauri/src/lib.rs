@@ -1,9 +1,273 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State};
+use tauri_plugin_shell::process::CommandChild;
+
 #[tauri::command]
 fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+/// A single line of sidecar output, forwarded to the frontend as a `backend-log` event.
+#[derive(Clone, Serialize)]
+struct BackendLogPayload {
+    stream: &'static str,
+    level: &'static str,
+    line: String,
+    ts: u64,
+}
+
+/// Emitted once as `backend-exit` when the sidecar process terminates.
+#[derive(Clone, Serialize)]
+struct BackendExitPayload {
+    code: Option<i32>,
+}
+
+/// One chunk of a streamed `ask_backend` answer, matched against stdout lines and
+/// re-emitted as `backend-response`. `id` ties each chunk back to the request that
+/// produced it so concurrent asks stay disentangled on the frontend.
+#[derive(Clone, Serialize, Deserialize)]
+struct BackendResponseChunk {
+    id: String,
+    chunk: String,
+    done: bool,
+}
+
+fn current_ts_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Best-effort detection of a log level, covering plain `ERROR`/`WARN` prefixes,
+/// `ERROR:`/`WARN:` markers anywhere in the line, and Python's `logging` module format
+/// (e.g. `2024-01-01 12:00:00,000 - name - ERROR - message`). Anything else is `info`.
+fn detect_level(line: &str) -> &'static str {
+    let upper = line.trim_start().to_uppercase();
+    if upper.starts_with("ERROR") || upper.contains(" - ERROR - ") || upper.contains("ERROR:") {
+        "error"
+    } else if upper.starts_with("WARN") || upper.contains(" - WARNING - ") || upper.contains("WARN:") {
+        "warn"
+    } else {
+        "info"
+    }
+}
+
+/// Holds the handle to the running backend sidecar so it can be stopped, restarted,
+/// or written to from `#[tauri::command]`s after launch, plus a watch channel that
+/// lets callers await the moment the backend's HTTP server comes up and the
+/// OS-assigned port it was told to bind.
+///
+/// `epoch` is bumped every time a process is spawned, stopped, or restarted. Each
+/// `spawn_backend` thread only trusts a readiness line if the epoch it captured at
+/// spawn time is still current, so a buffered line from a process that's already
+/// been killed can never clobber `ready` with a stale port.
+struct BackendState {
+    child: Mutex<Option<CommandChild>>,
+    ready: tokio::sync::watch::Sender<Option<u16>>,
+    port: AtomicU16,
+    epoch: AtomicU64,
+}
+
+/// Binds to an OS-assigned free port and releases it immediately so the sidecar can
+/// bind the same port itself, avoiding collisions when `8808` is already taken.
+fn pick_free_port() -> Result<u16, String> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").map_err(|e| e.to_string())?;
+    listener.local_addr().map_err(|e| e.to_string()).map(|addr| addr.port())
+}
+
+/// Recognizes the backend's readiness sentinel on stdout: either the plain
+/// `BACKEND READY` marker or a `{"event":"ready","port":N}` JSON line. Returns the
+/// port the backend reports, if any.
+fn parse_ready_line(line: &str, fallback_port: u16) -> Option<u16> {
+    let trimmed = line.trim();
+    if trimmed == "BACKEND READY" {
+        return Some(fallback_port);
+    }
+    serde_json::from_str::<serde_json::Value>(trimmed)
+        .ok()
+        .filter(|v| v.get("event").and_then(|e| e.as_str()) == Some("ready"))
+        .and_then(|v| v.get("port").and_then(|p| p.as_u64()))
+        .map(|p| p as u16)
+}
+
+/// Spawns the Python backend sidecar and forwards its output to the frontend as
+/// `backend-log`/`backend-exit` events. Returns the child handle so the caller can
+/// store it in `BackendState` for later lifecycle control.
+fn spawn_backend(app: &AppHandle) -> Result<CommandChild, String> {
+    use tauri::Emitter;
+    use tauri_plugin_shell::ShellExt;
+    use tauri_plugin_shell::process::CommandEvent;
+
+    let port = pick_free_port()?;
+    println!("Starting backend sidecar on port {}...", port);
+    let shell = app.shell();
+    let sidecar_command = shell.sidecar("backend").map_err(|e| {
+        eprintln!("Failed to create sidecar command: {}", e);
+        e.to_string()
+    })?;
+
+    let (mut rx, child) = sidecar_command
+        .args(["--port", &port.to_string()])
+        .spawn()
+        .map_err(|e| {
+            eprintln!("Failed to spawn sidecar: {}", e);
+            e.to_string()
+        })?;
+
+    let backend_state = app.state::<BackendState>();
+    backend_state.port.store(port, Ordering::SeqCst);
+    let _ = app.emit("backend-port", serde_json::json!({ "port": port }));
+    let my_epoch = backend_state.epoch.fetch_add(1, Ordering::SeqCst) + 1;
+
+    // Log sidecar output in a separate thread, forwarding each line to the
+    // frontend as a typed event so the web UI isn't blind to backend activity.
+    let app_handle = app.clone();
+    let ready_tx = backend_state.ready.clone();
+    std::thread::spawn(move || {
+        let mut ready_announced = false;
+        while let Some(event) = rx.blocking_recv() {
+            match event {
+                CommandEvent::Stdout(line) => {
+                    let line = String::from_utf8_lossy(&line).to_string();
+                    println!("[backend stdout] {}", line);
+                    if !ready_announced {
+                        if let Some(ready_port) = parse_ready_line(&line, port) {
+                            ready_announced = true;
+                            // Only trust this line if no restart/stop has happened
+                            // since this thread's process was spawned.
+                            if app_handle.state::<BackendState>().epoch.load(Ordering::SeqCst) == my_epoch {
+                                let _ = ready_tx.send(Some(ready_port));
+                                let _ = app_handle.emit("backend-ready", serde_json::json!({ "port": ready_port }));
+                            }
+                        }
+                    }
+                    // A response to an `ask_backend` request is routed to its own
+                    // event instead of being logged as plain sidecar output.
+                    match serde_json::from_str::<BackendResponseChunk>(line.trim()) {
+                        Ok(response) => {
+                            let _ = app_handle.emit("backend-response", response);
+                        }
+                        Err(_) => {
+                            let _ = app_handle.emit(
+                                "backend-log",
+                                BackendLogPayload {
+                                    stream: "stdout",
+                                    level: detect_level(&line),
+                                    line,
+                                    ts: current_ts_millis(),
+                                },
+                            );
+                        }
+                    }
+                }
+                CommandEvent::Stderr(line) => {
+                    let line = String::from_utf8_lossy(&line).to_string();
+                    eprintln!("[backend stderr] {}", line);
+                    let _ = app_handle.emit(
+                        "backend-log",
+                        BackendLogPayload {
+                            stream: "stderr",
+                            level: detect_level(&line),
+                            line,
+                            ts: current_ts_millis(),
+                        },
+                    );
+                }
+                CommandEvent::Terminated(payload) => {
+                    eprintln!("[backend] Process terminated with code: {:?}", payload.code);
+                    let _ = app_handle.emit("backend-exit", BackendExitPayload { code: payload.code });
+                }
+                _ => {}
+            }
+        }
+    });
+
+    println!("Backend sidecar started successfully");
+    Ok(child)
+}
+
+/// Kills the current backend sidecar (if any) and spawns a fresh one in its place.
+#[tauri::command]
+fn backend_restart(app: AppHandle, state: State<BackendState>) -> Result<(), String> {
+    let mut guard = state.child.lock().unwrap();
+    // Bump the epoch and clear readiness before killing, so a readiness line
+    // buffered from the outgoing process can't be mistaken for the new one.
+    state.epoch.fetch_add(1, Ordering::SeqCst);
+    let _ = state.ready.send(None);
+    if let Some(child) = guard.take() {
+        let _ = child.kill();
+    }
+    *guard = Some(spawn_backend(&app)?);
+    Ok(())
+}
+
+/// Stops the backend sidecar, leaving it unmanaged until `backend_restart` is called.
+#[tauri::command]
+fn backend_stop(state: State<BackendState>) -> Result<(), String> {
+    let mut guard = state.child.lock().unwrap();
+    // Same reasoning as `backend_restart`: invalidate the epoch so a late
+    // readiness line from the process being stopped is ignored.
+    state.epoch.fetch_add(1, Ordering::SeqCst);
+    let _ = state.ready.send(None);
+    if let Some(child) = guard.take() {
+        child.kill().map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Writes a single line to the backend sidecar's stdin, appending a trailing
+/// newline so a backend reading stdin line by line sees a complete line (callers
+/// pass just the line's content, not the terminator).
+#[tauri::command]
+fn backend_write(line: String, state: State<BackendState>) -> Result<(), String> {
+    let mut guard = state.child.lock().unwrap();
+    let child = guard.as_mut().ok_or("backend is not running")?;
+    let mut line = line;
+    line.push('\n');
+    child.write(line.as_bytes()).map_err(|e| e.to_string())
+}
+
+/// Returns the port the currently running (or most recently spawned) backend
+/// sidecar was told to bind, so the frontend can build its base URL dynamically.
+#[tauri::command]
+fn backend_port(state: State<BackendState>) -> u16 {
+    state.port.load(Ordering::SeqCst)
+}
+
+/// Writes a single `{"id", "prompt"}` JSON line to the backend's stdin, kicking off a
+/// streamed answer. The stdout reader spawned in `spawn_backend` matches response
+/// lines by `id` and re-emits them as `backend-response`, so no per-request channel
+/// is kept here — the `id` tag is what keeps concurrent asks disentangled.
+#[tauri::command]
+fn ask_backend(prompt: String, request_id: String, state: State<BackendState>) -> Result<(), String> {
+    let mut guard = state.child.lock().unwrap();
+    let child = guard.as_mut().ok_or("backend is not running")?;
+    let mut line = serde_json::json!({ "id": request_id, "prompt": prompt }).to_string();
+    line.push('\n');
+    child.write(line.as_bytes()).map_err(|e| e.to_string())
+}
+
+/// Resolves once the backend has reported readiness, so the frontend can await this
+/// before issuing its first request instead of racing the sidecar's startup.
+#[tauri::command]
+async fn wait_for_backend(state: State<'_, BackendState>) -> Result<u16, String> {
+    let mut rx = state.ready.subscribe();
+    if let Some(port) = *rx.borrow() {
+        return Ok(port);
+    }
+    loop {
+        rx.changed().await.map_err(|e| e.to_string())?;
+        if let Some(port) = *rx.borrow() {
+            return Ok(port);
+        }
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -12,54 +276,102 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_mic_recorder::init())
-        .setup(|_app| {
-            // Only spawn sidecar in release mode
-            // In dev mode, run the backend manually with hot reload:
-            // cd backend && uv run dev
-            #[cfg(not(debug_assertions))]
-            {
-                use tauri_plugin_shell::ShellExt;
-                use tauri_plugin_shell::process::CommandEvent;
-                
-                println!("Starting backend sidecar...");
-                let shell = _app.shell();
-                let sidecar_command = shell.sidecar("backend").map_err(|e| {
-                    eprintln!("Failed to create sidecar command: {}", e);
-                    e.to_string()
-                })?;
-                
-                let (mut rx, _child) = sidecar_command
-                    .args(["--port", "8808"])
-                    .spawn()
-                    .map_err(|e| {
-                        eprintln!("Failed to spawn sidecar: {}", e);
-                        e.to_string()
-                    })?;
-                
-                // Log sidecar output in a separate thread
-                std::thread::spawn(move || {
-                    while let Some(event) = rx.blocking_recv() {
-                        match event {
-                            CommandEvent::Stdout(line) => {
-                                println!("[backend stdout] {}", String::from_utf8_lossy(&line));
-                            }
-                            CommandEvent::Stderr(line) => {
-                                eprintln!("[backend stderr] {}", String::from_utf8_lossy(&line));
-                            }
-                            CommandEvent::Terminated(payload) => {
-                                eprintln!("[backend] Process terminated with code: {:?}", payload.code);
-                            }
-                            _ => {}
-                        }
-                    }
-                });
-                
-                println!("Backend sidecar started successfully");
+        .manage({
+            let (ready, _) = tokio::sync::watch::channel(None);
+            BackendState {
+                child: Mutex::new(None),
+                ready,
+                port: AtomicU16::new(0),
+                epoch: AtomicU64::new(0),
+            }
+        })
+        .setup(|app| {
+            // Always spawn in release mode. In dev mode, the backend is normally run
+            // manually with hot reload (`cd backend && uv run dev`); set
+            // DATABAO_SPAWN_BACKEND=1 to spawn the sidecar automatically instead.
+            let should_spawn =
+                !cfg!(debug_assertions) || std::env::var("DATABAO_SPAWN_BACKEND").as_deref() == Ok("1");
+            if should_spawn {
+                let child = spawn_backend(&app.handle().clone())?;
+                *app.state::<BackendState>().child.lock().unwrap() = Some(child);
+            } else {
+                println!(
+                    "Skipping backend sidecar in dev mode (set DATABAO_SPAWN_BACKEND=1 to spawn it, \
+                     or run `cd backend && uv run dev` manually)."
+                );
             }
 
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![greet])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            backend_restart,
+            backend_stop,
+            backend_write,
+            backend_port,
+            wait_for_backend,
+            ask_backend
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| {
+            // Make sure the Python sidecar doesn't leak as an orphan process when the
+            // window closes.
+            if let tauri::RunEvent::ExitRequested { .. } = event {
+                if let Some(state) = app_handle.try_state::<BackendState>() {
+                    if let Some(child) = state.child.lock().unwrap().take() {
+                        let _ = child.kill();
+                    }
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_level_matches_python_logging_error() {
+        let line = "2024-01-01 12:00:00,000 - myapp - ERROR - something broke";
+        assert_eq!(detect_level(line), "error");
+    }
+
+    #[test]
+    fn detect_level_matches_python_logging_warning() {
+        let line = "2024-01-01 12:00:00,000 - myapp - WARNING - low disk space";
+        assert_eq!(detect_level(line), "warn");
+    }
+
+    #[test]
+    fn detect_level_matches_plain_error_prefix() {
+        assert_eq!(detect_level("ERROR: failed to bind socket"), "error");
+    }
+
+    #[test]
+    fn detect_level_defaults_to_info() {
+        assert_eq!(detect_level("Server started on port 1234"), "info");
+    }
+
+    #[test]
+    fn parse_ready_line_matches_plain_sentinel() {
+        assert_eq!(parse_ready_line("BACKEND READY", 1234), Some(1234));
+    }
+
+    #[test]
+    fn parse_ready_line_matches_ready_json() {
+        let line = r#"{"event":"ready","port":5555}"#;
+        assert_eq!(parse_ready_line(line, 1234), Some(5555));
+    }
+
+    #[test]
+    fn parse_ready_line_ignores_non_ready_json() {
+        let line = r#"{"event":"log","message":"starting up"}"#;
+        assert_eq!(parse_ready_line(line, 1234), None);
+    }
+
+    #[test]
+    fn parse_ready_line_ignores_unrelated_lines() {
+        assert_eq!(parse_ready_line("Server started on port 1234", 1234), None);
+    }
 }